@@ -1,22 +1,205 @@
 use std::cell::RefCell;
 use wasm_bindgen::prelude::*;
-use web_sys::{WebGl2RenderingContext, WebGlProgram, WebGlShader, WebGlBuffer, WebGlUniformLocation};
+use web_sys::{
+    CanvasRenderingContext2d, HtmlCanvasElement, WebGl2RenderingContext, WebGlBuffer,
+    WebGlFramebuffer, WebGlProgram, WebGlShader, WebGlTexture, WebGlUniformLocation,
+};
 
 // Number of bars - use full resolution
 const NUM_BARS: usize = 128;
 
-// Store WebGL state
+// Number of one-sided weights in the separable Gaussian blur kernel: the center tap plus
+// BLUR_TAPS - 1 taps on each side.
+const BLUR_TAPS: usize = 11;
+
+// Store visualizer state
 thread_local! {
     static STATE: RefCell<Option<VisualizerState>> = RefCell::new(None);
 }
 
-struct VisualizerState {
+/// The two rendering backends. GL is the default; Canvas2d is a software fallback for browsers
+/// or locked-down contexts that don't expose WebGL2, so the visualizer degrades instead of
+/// failing outright.
+enum VisualizerState {
+    // Both variants embed a Dynamics (two `[f32; NUM_BARS]` arrays), so they're both large;
+    // box them so the enum itself stays small regardless of which backend is active.
+    Gl(Box<GlState>),
+    Canvas2d(Box<Canvas2dState>),
+}
+
+// Audio-shaping state shared by both backends: per-bar smoothing/peak-hold and the FFT bin
+// layout. None of this cares which backend draws the result.
+struct Dynamics {
+    // Per-bar temporal smoothing: a level that eases toward the incoming value, and a
+    // slower-falling peak marker rendered as a detached cap above/below the bar.
+    smoothed: [f32; NUM_BARS],
+    peak: [f32; NUM_BARS],
+    attack: f32,
+    release: f32,
+    gravity: f32,
+
+    // Maps each bar to a range of source FFT bins. Rebuilt only when the scale mode,
+    // frequency range, or the incoming frequency_data length changes.
+    scale_mode: ScaleMode,
+    min_hz: f32,
+    max_hz: f32,
+    sample_rate: f32,
+    bin_ranges: Vec<(usize, usize)>,
+    bin_ranges_len: usize,
+    bins_dirty: bool,
+
+    // Corner radius, in pixels, for the bar capsules. Shared because both backends draw the
+    // same rounded-pill shape.
+    corner_radius: f32,
+}
+
+fn new_dynamics() -> Dynamics {
+    Dynamics {
+        smoothed: [0.0; NUM_BARS],
+        peak: [0.0; NUM_BARS],
+        attack: 0.6,
+        release: 0.15,
+        gravity: 1.5,
+        scale_mode: ScaleMode::Linear,
+        min_hz: 20.0,
+        max_hz: 20000.0,
+        sample_rate: 44100.0,
+        bin_ranges: Vec::new(),
+        bin_ranges_len: 0,
+        bins_dirty: true,
+        corner_radius: 6.0,
+    }
+}
+
+fn dynamics_mut(state: &mut VisualizerState) -> &mut Dynamics {
+    match state {
+        VisualizerState::Gl(gl_state) => &mut gl_state.dynamics,
+        VisualizerState::Canvas2d(c) => &mut c.dynamics,
+    }
+}
+
+struct GlState {
     gl: WebGl2RenderingContext,
     program: WebGlProgram,
-    vertex_buffer: WebGlBuffer,
+    // Kept so a new fragment shader can be re-linked against the same vertex shader.
+    vert_shader: WebGlShader,
+    // The fragment shader currently linked into `program`; deleted once set_fragment_shader()
+    // supersedes it with a newly compiled one.
+    frag_shader: WebGlShader,
+    quad_buffer: WebGlBuffer,
+    instance_index_buffer: WebGlBuffer,
+    instance_value_buffer: WebGlBuffer,
     resolution_loc: WebGlUniformLocation,
+    padding_x_loc: WebGlUniformLocation,
+    bar_spacing_loc: WebGlUniformLocation,
+    bar_half_width_loc: WebGlUniformLocation,
+    max_height_loc: WebGlUniformLocation,
+    cy_loc: WebGlUniformLocation,
+    // Fragment-shader uniforms; absent when a custom shader doesn't declare them.
+    corner_radius_loc: Option<WebGlUniformLocation>,
+    time_loc: Option<WebGlUniformLocation>,
+    time_elapsed: f32,
+    canvas_width: f32,
+    canvas_height: f32,
+    bloom: Bloom,
+    peak_caps: PeakCaps,
+    dynamics: Dynamics,
+}
+
+// Software fallback used when the context/browser doesn't expose WebGL2. Draws the same
+// rounded-pill bars via the 2D canvas API, approximating the GL bloom with `shadow_blur`.
+struct Canvas2dState {
+    ctx: CanvasRenderingContext2d,
     canvas_width: f32,
     canvas_height: f32,
+    dynamics: Dynamics,
+}
+
+// Shared bar-placement math: padding, spacing, and max height, derived from canvas size. Used
+// by both the GL vertex shader uniforms and the Canvas2d path so the two backends line up.
+struct BarLayout {
+    padding_x: f32,
+    bar_spacing: f32,
+    bar_half_width: f32,
+    max_height: f32,
+    cy: f32,
+}
+
+fn compute_bar_layout(canvas_width: f32, canvas_height: f32) -> BarLayout {
+    let cy = canvas_height / 2.0;
+
+    // We want the spectrum to span the width, with some padding
+    let padding_x = canvas_width * 0.05;
+    let total_width = canvas_width - (2.0 * padding_x);
+    let bar_spacing = total_width / NUM_BARS as f32;
+    let bar_width = bar_spacing * 0.7; // 70% bar, 30% gap
+    let bar_half_width = bar_width / 2.0;
+
+    let max_height = canvas_height * 0.35; // Leave some room
+
+    BarLayout {
+        padding_x,
+        bar_spacing,
+        bar_half_width,
+        max_height,
+        cy,
+    }
+}
+
+/// How FFT bins are distributed across bars. Linear mirrors raw bin spacing; `Log` and `Mel`
+/// spread low-frequency content (where most musical energy lives) across more bars.
+#[wasm_bindgen]
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum ScaleMode {
+    Linear,
+    Log,
+    Mel,
+}
+
+// Thin detached lines drawn at each bar's peak-hold height, classic spectrum-analyzer style.
+struct PeakCaps {
+    program: WebGlProgram,
+    instance_peak_buffer: WebGlBuffer,
+    resolution_loc: WebGlUniformLocation,
+    padding_x_loc: WebGlUniformLocation,
+    bar_spacing_loc: WebGlUniformLocation,
+    bar_half_width_loc: WebGlUniformLocation,
+    max_height_loc: WebGlUniformLocation,
+    cy_loc: WebGlUniformLocation,
+    thickness_loc: WebGlUniformLocation,
+    sign_loc: WebGlUniformLocation,
+    thickness: f32,
+}
+
+// Offscreen pipeline: bars -> bright-pass -> horizontal blur -> vertical blur -> additive composite.
+struct Bloom {
+    scene_fbo: WebGlFramebuffer,
+    scene_tex: WebGlTexture,
+    bright_fbo: WebGlFramebuffer,
+    bright_tex: WebGlTexture,
+    blur_fbo_a: WebGlFramebuffer,
+    blur_tex_a: WebGlTexture,
+    blur_fbo_b: WebGlFramebuffer,
+    blur_tex_b: WebGlTexture,
+
+    quad_buffer: WebGlBuffer,
+
+    bright_program: WebGlProgram,
+    bright_resolution_loc: WebGlUniformLocation,
+    bright_threshold_loc: WebGlUniformLocation,
+
+    blur_program: WebGlProgram,
+    blur_resolution_loc: WebGlUniformLocation,
+    blur_direction_loc: WebGlUniformLocation,
+    blur_weights_loc: WebGlUniformLocation,
+
+    composite_program: WebGlProgram,
+    composite_intensity_loc: WebGlUniformLocation,
+
+    threshold: f32,
+    sigma: f32,
+    intensity: f32,
+    weights: Vec<f32>,
 }
 
 #[wasm_bindgen(start)]
@@ -26,214 +209,972 @@ pub fn start() -> Result<(), JsValue> {
     let canvas = document
         .get_element_by_id("canvas")
         .ok_or("No canvas")?
-        .dyn_into::<web_sys::HtmlCanvasElement>()?;
-    
-    let gl = canvas
-        .get_context("webgl2")?
-        .ok_or("No WebGL2")?
-        .dyn_into::<WebGl2RenderingContext>()?;
-    
-    // Vertex shader
+        .dyn_into::<HtmlCanvasElement>()?;
+
+    // Prefer WebGL2; fall back to a plain 2D canvas when it's unavailable so the visualizer
+    // still renders, just without the instanced pipeline and bloom pass.
+    let visualizer_state = if let Some(ctx) = canvas.get_context("webgl2")? {
+        let gl = ctx.dyn_into::<WebGl2RenderingContext>()?;
+        VisualizerState::Gl(Box::new(init_gl(&canvas, gl)?))
+    } else {
+        let ctx = canvas
+            .get_context("2d")?
+            .ok_or("No 2D context available either")?
+            .dyn_into::<CanvasRenderingContext2d>()?;
+        VisualizerState::Canvas2d(Box::new(Canvas2dState {
+            ctx,
+            canvas_width: canvas.width() as f32,
+            canvas_height: canvas.height() as f32,
+            dynamics: new_dynamics(),
+        }))
+    };
+
+    STATE.with(|s| {
+        *s.borrow_mut() = Some(visualizer_state);
+    });
+
+    Ok(())
+}
+
+fn init_gl(canvas: &HtmlCanvasElement, gl: WebGl2RenderingContext) -> Result<GlState, JsValue> {
+    // Vertex shader - draws one instance of a static unit quad per bar. All per-bar layout
+    // math (position, height, half-extent) happens here from uniforms + the per-instance
+    // value/index, so the bar body only needs NUM_BARS floats uploaded per frame.
     let vert_src = r#"#version 300 es
         precision highp float;
-        
-        in vec2 a_position;
-        in float a_value;
-        in float a_index;
-        
+
+        in vec2 a_quad_pos;   // unit quad corner in [-1, 1], divisor 0 (same for every instance)
+        in float a_value;     // per-instance smoothed bar level, divisor 1
+        in float a_bar_index; // per-instance bar index, divisor 1
+
         uniform vec2 u_resolution;
-        
+        uniform float u_padding_x;
+        uniform float u_bar_spacing;
+        uniform float u_bar_half_width;
+        uniform float u_max_height;
+        uniform float u_cy;
+
         out float v_value;
         out float v_index;
         out vec2 v_pos;
-        
+        out vec2 v_local;
+        out vec2 v_half_extent;
+
         void main() {
+            // a_value is already gamma-corrected and temporally smoothed on the CPU side.
+            float h = 4.0 + a_value * u_max_height; // Minimum 4px height
+            vec2 halfExtent = vec2(u_bar_half_width, h);
+            vec2 local = a_quad_pos * halfExtent;
+
+            float xCenter = u_padding_x + a_bar_index * u_bar_spacing + u_bar_spacing * 0.5;
+            vec2 worldPos = vec2(xCenter, u_cy) + local;
+
             v_value = a_value;
-            v_index = a_index;
-            v_pos = a_position;
-            
-            vec2 clipSpace = (a_position / u_resolution) * 2.0 - 1.0;
+            v_index = a_bar_index;
+            v_pos = worldPos;
+            v_local = local;
+            v_half_extent = halfExtent;
+
+            vec2 clipSpace = (worldPos / u_resolution) * 2.0 - 1.0;
             gl_Position = vec4(clipSpace * vec2(1, -1), 0, 1);
         }
     "#;
-    
-    // Fragment shader - Neon pill bars
+
+    // Fragment shader - Neon pill bars, shaped by a rounded-rect SDF for crisp capsule tips
     let frag_src = r#"#version 300 es
         precision highp float;
-        
+
         in float v_value;
         in float v_index;
         in vec2 v_pos;
-        
+        in vec2 v_local;
+        in vec2 v_half_extent;
+
+        uniform float u_corner_radius;
+
         out vec4 fragColor;
-        
+
+        // Signed distance to a rounded rect of half-extent b and corner radius r, centered at origin.
+        float roundedRectSdf(vec2 p, vec2 b, float r) {
+            vec2 q = abs(p) - b + r;
+            return length(max(q, 0.0)) - r;
+        }
+
         void main() {
             // Gradient: Cyan -> Indigo -> Pink
             vec3 c1 = vec3(0.0, 0.84, 1.0);    // Cyan #00d7ff
             vec3 c2 = vec3(0.39, 0.4, 0.95);   // Indigo #6366f1
             vec3 c3 = vec3(1.0, 0.18, 0.58);   // Pink #ff2d95
-            
+
             // Interpolate color based on bar index (left to right)
             float t = v_index / 128.0;
             vec3 color = mix(c1, c2, smoothstep(0.0, 0.5, t));
             color = mix(color, c3, smoothstep(0.5, 1.0, t));
-            
-            // Add glow intensity based on volume
+
+            // Volume lifts brightness a little; the real bloom is a separate offscreen pass now.
             float glow = 0.5 + v_value * 0.5;
-            color *= glow; // Bloom effect
-            
-            // Vertical fade for softness at tips
-            // Assuming bars are centered at Y, we can cheat by just using solid color
-            // as the shape is defined by geometry.
-            
+            color *= glow;
+
+            float r = min(u_corner_radius, min(v_half_extent.x, v_half_extent.y));
+            float d = roundedRectSdf(v_local, v_half_extent, r);
+            float aa = fwidth(d);
+            float coverage = 1.0 - smoothstep(-aa, aa, d);
+            if (coverage <= 0.0) discard;
+
             // Slight transparency for glass feel
-            float alpha = 0.9 + v_value * 0.1;
-            
+            float alpha = (0.9 + v_value * 0.1) * coverage;
+
             fragColor = vec4(color, alpha);
         }
     "#;
-    
+
     let vert_shader = compile_shader(&gl, WebGl2RenderingContext::VERTEX_SHADER, vert_src)?;
     let frag_shader = compile_shader(&gl, WebGl2RenderingContext::FRAGMENT_SHADER, frag_src)?;
     let program = link_program(&gl, &vert_shader, &frag_shader)?;
-    
+
     gl.use_program(Some(&program));
-    
+
+    // Layout uniforms live in the vertex shader, which custom fragment shaders never replace,
+    // so they're always present.
     let resolution_loc = gl.get_uniform_location(&program, "u_resolution")
         .ok_or("No resolution uniform")?;
-    
-    let vertex_buffer = gl.create_buffer().ok_or("Failed to create buffer")?;
-    
-    let pos_loc = gl.get_attrib_location(&program, "a_position") as u32;
-    let val_loc = gl.get_attrib_location(&program, "a_value") as u32;
-    let idx_loc = gl.get_attrib_location(&program, "a_index") as u32;
-    
-    gl.bind_buffer(WebGl2RenderingContext::ARRAY_BUFFER, Some(&vertex_buffer));
-    
-    let stride = 4 * 4;
-    gl.vertex_attrib_pointer_with_i32(pos_loc, 2, WebGl2RenderingContext::FLOAT, false, stride, 0);
-    gl.vertex_attrib_pointer_with_i32(val_loc, 1, WebGl2RenderingContext::FLOAT, false, stride, 8);
-    gl.vertex_attrib_pointer_with_i32(idx_loc, 1, WebGl2RenderingContext::FLOAT, false, stride, 12);
-    
-    gl.enable_vertex_attrib_array(pos_loc);
-    gl.enable_vertex_attrib_array(val_loc);
-    gl.enable_vertex_attrib_array(idx_loc);
-    
+    let padding_x_loc = gl.get_uniform_location(&program, "u_padding_x")
+        .ok_or("No padding uniform")?;
+    let bar_spacing_loc = gl.get_uniform_location(&program, "u_bar_spacing")
+        .ok_or("No bar spacing uniform")?;
+    let bar_half_width_loc = gl.get_uniform_location(&program, "u_bar_half_width")
+        .ok_or("No bar half width uniform")?;
+    let max_height_loc = gl.get_uniform_location(&program, "u_max_height")
+        .ok_or("No max height uniform")?;
+    let cy_loc = gl.get_uniform_location(&program, "u_cy")
+        .ok_or("No cy uniform")?;
+    // Fragment-shader uniforms: only guaranteed to exist while the stock fragment shader is
+    // active, so a custom shader that omits them just leaves these as None.
+    let corner_radius_loc = gl.get_uniform_location(&program, "u_corner_radius");
+    let time_loc = gl.get_uniform_location(&program, "u_time");
+
+    // Static unit quad, shared by every bar instance.
+    let quad_buffer = gl.create_buffer().ok_or("Failed to create quad buffer")?;
+    gl.bind_buffer(WebGl2RenderingContext::ARRAY_BUFFER, Some(&quad_buffer));
+    let quad: [f32; 12] = [
+        -1.0, -1.0, 1.0, -1.0, -1.0, 1.0,
+        1.0, -1.0, 1.0, 1.0, -1.0, 1.0,
+    ];
+    unsafe {
+        let quad_array = js_sys::Float32Array::view(&quad);
+        gl.buffer_data_with_array_buffer_view(
+            WebGl2RenderingContext::ARRAY_BUFFER,
+            &quad_array,
+            WebGl2RenderingContext::STATIC_DRAW,
+        );
+    }
+
+    // Static per-instance bar index (0..NUM_BARS), never changes.
+    let instance_index_buffer = gl.create_buffer().ok_or("Failed to create instance index buffer")?;
+    gl.bind_buffer(WebGl2RenderingContext::ARRAY_BUFFER, Some(&instance_index_buffer));
+    let indices: Vec<f32> = (0..NUM_BARS).map(|i| i as f32).collect();
+    unsafe {
+        let index_array = js_sys::Float32Array::view(&indices);
+        gl.buffer_data_with_array_buffer_view(
+            WebGl2RenderingContext::ARRAY_BUFFER,
+            &index_array,
+            WebGl2RenderingContext::STATIC_DRAW,
+        );
+    }
+
+    // Dynamic per-instance value, re-uploaded every frame (the peak caps re-upload their own).
+    let instance_value_buffer = gl.create_buffer().ok_or("Failed to create instance value buffer")?;
+
+    bind_bar_attribs(&gl, &program, &quad_buffer, &instance_index_buffer, &instance_value_buffer);
+
     gl.enable(WebGl2RenderingContext::BLEND);
     gl.blend_func(WebGl2RenderingContext::SRC_ALPHA, WebGl2RenderingContext::ONE_MINUS_SRC_ALPHA);
-    
+
     let width = canvas.width() as f32;
     let height = canvas.height() as f32;
-    
-    STATE.with(|s| {
-        *s.borrow_mut() = Some(VisualizerState {
-            gl,
-            program,
-            vertex_buffer,
-            resolution_loc,
-            canvas_width: width,
-            canvas_height: height,
-        });
-    });
-    
-    Ok(())
+
+    let bloom = create_bloom(&gl, width, height)?;
+    let peak_caps = create_peak_caps(&gl, &quad_buffer, &instance_index_buffer)?;
+
+    Ok(GlState {
+        gl,
+        program,
+        vert_shader,
+        frag_shader,
+        quad_buffer,
+        instance_index_buffer,
+        instance_value_buffer,
+        resolution_loc,
+        corner_radius_loc,
+        padding_x_loc,
+        bar_spacing_loc,
+        bar_half_width_loc,
+        max_height_loc,
+        cy_loc,
+        time_loc,
+        canvas_width: width,
+        canvas_height: height,
+        bloom,
+        peak_caps,
+        dynamics: new_dynamics(),
+        time_elapsed: 0.0,
+    })
 }
 
 #[wasm_bindgen]
-pub fn render_frame(frequency_data: &[u8], _time_data: &[u8]) {
+pub fn render_frame(frequency_data: &[u8], _time_data: &[u8], dt: f32) {
     STATE.with(|s| {
         let mut state_ref = s.borrow_mut();
         if let Some(state) = state_ref.as_mut() {
-            render_linear_visualizer(state, frequency_data);
+            match state {
+                VisualizerState::Gl(gl_state) => {
+                    gl_state.time_elapsed += dt;
+                    update_dynamics(&mut gl_state.dynamics, frequency_data, dt);
+                    render_linear_visualizer(gl_state);
+                }
+                VisualizerState::Canvas2d(c) => {
+                    update_dynamics(&mut c.dynamics, frequency_data, dt);
+                    render_canvas2d(c);
+                }
+            }
         }
     });
 }
 
+/// Tune the per-bar smoothing. `attack` and `release` are the easing coefficients applied
+/// when a bar's incoming value is rising/falling; `gravity` is how fast the peak-hold marker
+/// falls per second once nothing is pushing it back up.
+#[wasm_bindgen]
+pub fn set_dynamics(attack: f32, release: f32, gravity: f32) {
+    STATE.with(|s| {
+        if let Some(state) = s.borrow_mut().as_mut() {
+            let dynamics = dynamics_mut(state);
+            dynamics.attack = attack;
+            dynamics.release = release;
+            dynamics.gravity = gravity;
+        }
+    });
+}
+
+/// Choose how FFT bins are distributed across bars.
+#[wasm_bindgen]
+pub fn set_scale(mode: ScaleMode) {
+    STATE.with(|s| {
+        if let Some(state) = s.borrow_mut().as_mut() {
+            let dynamics = dynamics_mut(state);
+            if dynamics.scale_mode != mode {
+                dynamics.scale_mode = mode;
+                dynamics.bins_dirty = true;
+            }
+        }
+    });
+}
+
+/// Restrict the spectrum to `[min_hz, max_hz]`, skipping DC/hum at the low end and dead
+/// high frequencies. `sample_rate` is needed to convert Hz to FFT bin indices.
+#[wasm_bindgen]
+pub fn set_frequency_range(min_hz: f32, max_hz: f32, sample_rate: f32) {
+    STATE.with(|s| {
+        if let Some(state) = s.borrow_mut().as_mut() {
+            let dynamics = dynamics_mut(state);
+            dynamics.min_hz = min_hz;
+            dynamics.max_hz = max_hz;
+            dynamics.sample_rate = sample_rate;
+            dynamics.bins_dirty = true;
+        }
+    });
+}
+
+fn update_dynamics(dynamics: &mut Dynamics, frequency_data: &[u8], dt: f32) {
+    if dynamics.bins_dirty || dynamics.bin_ranges_len != frequency_data.len() {
+        dynamics.bin_ranges = compute_bin_ranges(
+            frequency_data.len(),
+            dynamics.scale_mode,
+            dynamics.min_hz,
+            dynamics.max_hz,
+            dynamics.sample_rate,
+        );
+        dynamics.bin_ranges_len = frequency_data.len();
+        dynamics.bins_dirty = false;
+    }
+
+    for i in 0..NUM_BARS {
+        let (start, end) = dynamics.bin_ranges[i];
+        let raw = frequency_data[start..end]
+            .iter()
+            .map(|&b| b as f32 / 255.0)
+            .fold(0.0f32, f32::max);
+        let incoming = raw.powf(0.85); // Gamma correction
+
+        let current = dynamics.smoothed[i];
+        let coeff = if incoming > current { dynamics.attack } else { dynamics.release };
+        dynamics.smoothed[i] = current + coeff * (incoming - current);
+
+        dynamics.peak[i] = (dynamics.peak[i] - dynamics.gravity * dt).max(dynamics.smoothed[i]);
+    }
+}
+
+// Precompute, for each bar, the [start, end) range of source FFT bins it aggregates by max.
+fn compute_bin_ranges(
+    num_bins: usize,
+    scale: ScaleMode,
+    min_hz: f32,
+    max_hz: f32,
+    sample_rate: f32,
+) -> Vec<(usize, usize)> {
+    if num_bins < 2 {
+        return vec![(0, num_bins); NUM_BARS];
+    }
+
+    let nyquist = sample_rate / 2.0;
+    let hz_per_bin = nyquist / num_bins as f32;
+
+    let min_bin = (min_hz / hz_per_bin).max(1.0);
+    let max_bin = (max_hz / hz_per_bin).min(num_bins as f32).max(min_bin + 1.0);
+
+    let bin_at = |i: usize| -> f32 {
+        let t = i as f32 / NUM_BARS as f32;
+        match scale {
+            ScaleMode::Linear => min_bin + t * (max_bin - min_bin),
+            ScaleMode::Log => min_bin * (max_bin / min_bin).powf(t),
+            ScaleMode::Mel => {
+                let mel_min = hz_to_mel(min_bin * hz_per_bin);
+                let mel_max = hz_to_mel(max_bin * hz_per_bin);
+                mel_to_hz(mel_min + t * (mel_max - mel_min)) / hz_per_bin
+            }
+        }
+    };
+
+    (0..NUM_BARS)
+        .map(|i| {
+            let start = (bin_at(i).round() as usize).min(num_bins - 1);
+            let end = (bin_at(i + 1).round() as usize).max(start + 1).min(num_bins);
+            (start, end)
+        })
+        .collect()
+}
+
+fn hz_to_mel(hz: f32) -> f32 {
+    2595.0 * (1.0 + hz / 700.0).log10()
+}
+
+fn mel_to_hz(mel: f32) -> f32 {
+    700.0 * (10f32.powf(mel / 2595.0) - 1.0)
+}
+
 #[wasm_bindgen]
 pub fn update_canvas_size(width: f32, height: f32) {
     STATE.with(|s| {
         if let Some(state) = s.borrow_mut().as_mut() {
-            state.canvas_width = width;
-            state.canvas_height = height;
-            state.gl.viewport(0, 0, width as i32, height as i32);
+            match state {
+                VisualizerState::Gl(gl_state) => {
+                    gl_state.canvas_width = width;
+                    gl_state.canvas_height = height;
+                    gl_state.gl.viewport(0, 0, width as i32, height as i32);
+                    if let Ok(bloom) = create_bloom(&gl_state.gl, width, height) {
+                        let old_bloom = std::mem::replace(&mut gl_state.bloom, bloom);
+                        delete_bloom(&gl_state.gl, old_bloom);
+                    }
+                    // On failure, keep the old FBOs around rather than leaving the state
+                    // half-built; they'll just be the wrong size until the next resize succeeds.
+                }
+                VisualizerState::Canvas2d(c) => {
+                    c.canvas_width = width;
+                    c.canvas_height = height;
+                }
+            }
         }
     });
 }
 
-fn render_linear_visualizer(state: &mut VisualizerState, frequency_data: &[u8]) {
+/// Tune the bloom pass. `threshold` is the luminance cutoff for the bright-pass,
+/// `sigma` controls the Gaussian blur spread, `intensity` scales the additive composite.
+/// No-op on the Canvas2d fallback, which has no offscreen pipeline to tune.
+#[wasm_bindgen]
+pub fn set_bloom(threshold: f32, sigma: f32, intensity: f32) {
+    STATE.with(|s| {
+        if let Some(VisualizerState::Gl(gl_state)) = s.borrow_mut().as_mut() {
+            gl_state.bloom.threshold = threshold;
+            gl_state.bloom.intensity = intensity;
+            if gl_state.bloom.sigma != sigma {
+                gl_state.bloom.sigma = sigma;
+                gl_state.bloom.weights = gaussian_weights(sigma, BLUR_TAPS);
+            }
+        }
+    });
+}
+
+/// Corner radius, in pixels, for the bar capsules. `0` gives sharp rectangles,
+/// larger values round the sides until the bar becomes a full capsule/pill.
+#[wasm_bindgen]
+pub fn set_corner_radius(radius: f32) {
+    STATE.with(|s| {
+        if let Some(state) = s.borrow_mut().as_mut() {
+            dynamics_mut(state).corner_radius = radius.max(0.0);
+        }
+    });
+}
+
+/// Recompile and swap in a custom fragment shader (Shadertoy-style), re-linked against the
+/// existing vertex shader. The custom shader can rely on `in float v_value`, `in float v_index`,
+/// `in vec2 v_pos`, `uniform vec2 u_resolution`, and `uniform float u_time`. On a GLSL error the
+/// old program keeps rendering and the compile log is returned instead of panicking. Requires
+/// the GL backend; returns an error on the Canvas2d fallback.
+#[wasm_bindgen]
+pub fn set_fragment_shader(src: &str) -> Result<(), JsValue> {
+    STATE.with(|s| {
+        let mut state_ref = s.borrow_mut();
+        let state = state_ref.as_mut().ok_or("Visualizer not initialized")?;
+        let gl_state = match state {
+            VisualizerState::Gl(gl_state) => gl_state,
+            VisualizerState::Canvas2d(_) => {
+                return Err(JsValue::from_str(
+                    "Custom fragment shaders require a WebGL2 context",
+                ))
+            }
+        };
+
+        let frag_shader = compile_shader(&gl_state.gl, WebGl2RenderingContext::FRAGMENT_SHADER, src)?;
+        let program = link_program(&gl_state.gl, &gl_state.vert_shader, &frag_shader)?;
+
+        bind_bar_attribs(
+            &gl_state.gl,
+            &program,
+            &gl_state.quad_buffer,
+            &gl_state.instance_index_buffer,
+            &gl_state.instance_value_buffer,
+        );
+
+        let resolution_loc = gl_state.gl.get_uniform_location(&program, "u_resolution")
+            .ok_or("No resolution uniform")?;
+        let padding_x_loc = gl_state.gl.get_uniform_location(&program, "u_padding_x")
+            .ok_or("No padding uniform")?;
+        let bar_spacing_loc = gl_state.gl.get_uniform_location(&program, "u_bar_spacing")
+            .ok_or("No bar spacing uniform")?;
+        let bar_half_width_loc = gl_state.gl.get_uniform_location(&program, "u_bar_half_width")
+            .ok_or("No bar half width uniform")?;
+        let max_height_loc = gl_state.gl.get_uniform_location(&program, "u_max_height")
+            .ok_or("No max height uniform")?;
+        let cy_loc = gl_state.gl.get_uniform_location(&program, "u_cy")
+            .ok_or("No cy uniform")?;
+        let corner_radius_loc = gl_state.gl.get_uniform_location(&program, "u_corner_radius");
+        let time_loc = gl_state.gl.get_uniform_location(&program, "u_time");
+
+        // The new program linked successfully; release the one it replaces so repeated
+        // live-coded swaps don't leak a program/shader pair per iteration.
+        let old_program = std::mem::replace(&mut gl_state.program, program);
+        gl_state.gl.delete_program(Some(&old_program));
+        let old_frag_shader = std::mem::replace(&mut gl_state.frag_shader, frag_shader);
+        gl_state.gl.delete_shader(Some(&old_frag_shader));
+
+        gl_state.resolution_loc = resolution_loc;
+        gl_state.padding_x_loc = padding_x_loc;
+        gl_state.bar_spacing_loc = bar_spacing_loc;
+        gl_state.bar_half_width_loc = bar_half_width_loc;
+        gl_state.max_height_loc = max_height_loc;
+        gl_state.cy_loc = cy_loc;
+        gl_state.corner_radius_loc = corner_radius_loc;
+        gl_state.time_loc = time_loc;
+
+        Ok(())
+    })
+}
+
+fn render_linear_visualizer(state: &mut GlState) {
     let gl = &state.gl;
-    
-    // Clear
+    let layout = compute_bar_layout(state.canvas_width, state.canvas_height);
+
+    // Pass 1: render the bars into the offscreen scene texture.
+    gl.bind_framebuffer(WebGl2RenderingContext::FRAMEBUFFER, Some(&state.bloom.scene_fbo));
+    gl.viewport(0, 0, state.canvas_width as i32, state.canvas_height as i32);
+    gl.use_program(Some(&state.program));
     gl.clear_color(0.0, 0.0, 0.0, 0.0);
     gl.clear(WebGl2RenderingContext::COLOR_BUFFER_BIT);
-    
-    // Set uniforms
+
     gl.uniform2f(Some(&state.resolution_loc), state.canvas_width, state.canvas_height);
-    
-    let cy = state.canvas_height / 2.0;
-    
-    // We want the spectrum to span the width, with some padding
-    let padding_x = state.canvas_width * 0.05;
-    let total_width = state.canvas_width - (2.0 * padding_x);
-    let bar_spacing = total_width / NUM_BARS as f32;
-    let bar_width = bar_spacing * 0.7; // 70% bar, 30% gap
-    
-    let max_height = state.canvas_height * 0.35; // Leave some room
-    
-    let mut vertices: Vec<f32> = Vec::with_capacity(NUM_BARS * 6 * 4);
-    
-    for i in 0..NUM_BARS {
-        // Frequency mapping
-        // We often want to skip the very first few low bins as they can be DC offset or hum
-        // and maybe limit the top end.
-        // But for simplicity, let's just map 1:1 if we count 128 bins.
-        let freq_idx = i.min(frequency_data.len() - 1);
-        let value = frequency_data[freq_idx] as f32 / 255.0;
-        
-        let smoothed_value = value.powf(0.85); // Gamma correction
-        
-        // Calculate X position
-        let x_center = padding_x + (i as f32 * bar_spacing) + (bar_spacing / 2.0);
-        
-        // Bar half-height (mirrored)
-        let h = 4.0 + (smoothed_value * max_height); // Minimum 4px height
-        
-        // Coordinates for a centered rounded rect (simulated by simple rect)
-        let x1 = x_center - bar_width / 2.0;
-        let x2 = x_center + bar_width / 2.0;
-        let y_top = cy - h;
-        let y_bottom = cy + h;
-        
-        let idx = i as f32;
-        
-        // Triangle 1
-        vertices.extend_from_slice(&[x1, y_top, smoothed_value, idx]);
-        vertices.extend_from_slice(&[x2, y_top, smoothed_value, idx]);
-        vertices.extend_from_slice(&[x1, y_bottom, smoothed_value, idx]);
-        
-        // Triangle 2
-        vertices.extend_from_slice(&[x2, y_top, smoothed_value, idx]);
-        vertices.extend_from_slice(&[x2, y_bottom, smoothed_value, idx]);
-        vertices.extend_from_slice(&[x1, y_bottom, smoothed_value, idx]);
+    if let Some(loc) = state.corner_radius_loc.as_ref() {
+        gl.uniform1f(Some(loc), state.dynamics.corner_radius);
     }
-    
+    if let Some(loc) = state.time_loc.as_ref() {
+        gl.uniform1f(Some(loc), state.time_elapsed);
+    }
+
+    gl.uniform1f(Some(&state.padding_x_loc), layout.padding_x);
+    gl.uniform1f(Some(&state.bar_spacing_loc), layout.bar_spacing);
+    gl.uniform1f(Some(&state.bar_half_width_loc), layout.bar_half_width);
+    gl.uniform1f(Some(&state.max_height_loc), layout.max_height);
+    gl.uniform1f(Some(&state.cy_loc), layout.cy);
+
+    // The bar body's only per-frame upload: one smoothed value per bar. Layout (position,
+    // half-extent) is computed on the GPU from the uniforms above plus the static
+    // per-instance bar index.
+    gl.bind_buffer(WebGl2RenderingContext::ARRAY_BUFFER, Some(&state.instance_value_buffer));
     unsafe {
-        let vert_array = js_sys::Float32Array::view(&vertices);
+        let value_array = js_sys::Float32Array::view(&state.dynamics.smoothed[..]);
         gl.buffer_data_with_array_buffer_view(
             WebGl2RenderingContext::ARRAY_BUFFER,
-            &vert_array,
+            &value_array,
             WebGl2RenderingContext::DYNAMIC_DRAW,
         );
     }
-    
-    gl.draw_arrays(WebGl2RenderingContext::TRIANGLES, 0, (NUM_BARS * 6) as i32);
+
+    gl.draw_arrays_instanced(WebGl2RenderingContext::TRIANGLES, 0, 6, NUM_BARS as i32);
+
+    render_peak_caps(
+        state,
+        layout.padding_x,
+        layout.bar_spacing,
+        layout.bar_half_width,
+        layout.max_height,
+        layout.cy,
+    );
+
+    // Passes 2-5: bright-pass, then two-pass separable blur, then additive composite to screen.
+    run_bloom_pipeline(state);
+}
+
+fn render_peak_caps(
+    state: &mut GlState,
+    padding_x: f32,
+    bar_spacing: f32,
+    bar_half_width: f32,
+    max_height: f32,
+    cy: f32,
+) {
+    let gl = &state.gl;
+    let caps = &state.peak_caps;
+
+    gl.use_program(Some(&caps.program));
+    gl.uniform2f(Some(&caps.resolution_loc), state.canvas_width, state.canvas_height);
+    gl.uniform1f(Some(&caps.padding_x_loc), padding_x);
+    gl.uniform1f(Some(&caps.bar_spacing_loc), bar_spacing);
+    gl.uniform1f(Some(&caps.bar_half_width_loc), bar_half_width);
+    gl.uniform1f(Some(&caps.max_height_loc), max_height);
+    gl.uniform1f(Some(&caps.cy_loc), cy);
+    gl.uniform1f(Some(&caps.thickness_loc), caps.thickness);
+
+    gl.bind_buffer(WebGl2RenderingContext::ARRAY_BUFFER, Some(&caps.instance_peak_buffer));
+    unsafe {
+        let peak_array = js_sys::Float32Array::view(&state.dynamics.peak[..]);
+        gl.buffer_data_with_array_buffer_view(
+            WebGl2RenderingContext::ARRAY_BUFFER,
+            &peak_array,
+            WebGl2RenderingContext::DYNAMIC_DRAW,
+        );
+    }
+
+    // Draw the cap mirrored above and below the bar's centerline, same as the bar body.
+    gl.uniform1f(Some(&caps.sign_loc), -1.0);
+    gl.draw_arrays_instanced(WebGl2RenderingContext::TRIANGLES, 0, 6, NUM_BARS as i32);
+    gl.uniform1f(Some(&caps.sign_loc), 1.0);
+    gl.draw_arrays_instanced(WebGl2RenderingContext::TRIANGLES, 0, 6, NUM_BARS as i32);
+}
+
+fn run_bloom_pipeline(state: &mut GlState) {
+    let gl = &state.gl;
+    let bloom = &state.bloom;
+    let width = state.canvas_width;
+    let height = state.canvas_height;
+
+    gl.bind_buffer(WebGl2RenderingContext::ARRAY_BUFFER, Some(&bloom.quad_buffer));
+    gl.disable(WebGl2RenderingContext::BLEND);
+
+    // Bright-pass: keep only pixels above the luminance threshold.
+    gl.bind_framebuffer(WebGl2RenderingContext::FRAMEBUFFER, Some(&bloom.bright_fbo));
+    gl.use_program(Some(&bloom.bright_program));
+    bind_quad_attribs(gl, &bloom.bright_program);
+    gl.active_texture(WebGl2RenderingContext::TEXTURE0);
+    gl.bind_texture(WebGl2RenderingContext::TEXTURE_2D, Some(&bloom.scene_tex));
+    gl.uniform2f(Some(&bloom.bright_resolution_loc), width, height);
+    gl.uniform1f(Some(&bloom.bright_threshold_loc), bloom.threshold);
+    gl.draw_arrays(WebGl2RenderingContext::TRIANGLES, 0, 6);
+
+    // Horizontal blur: bright_tex -> blur_tex_a
+    gl.bind_framebuffer(WebGl2RenderingContext::FRAMEBUFFER, Some(&bloom.blur_fbo_a));
+    gl.use_program(Some(&bloom.blur_program));
+    bind_quad_attribs(gl, &bloom.blur_program);
+    gl.active_texture(WebGl2RenderingContext::TEXTURE0);
+    gl.bind_texture(WebGl2RenderingContext::TEXTURE_2D, Some(&bloom.bright_tex));
+    gl.uniform2f(Some(&bloom.blur_resolution_loc), width, height);
+    gl.uniform2f(Some(&bloom.blur_direction_loc), 1.0, 0.0);
+    gl.uniform1fv_with_f32_array(Some(&bloom.blur_weights_loc), &bloom.weights);
+    gl.draw_arrays(WebGl2RenderingContext::TRIANGLES, 0, 6);
+
+    // Vertical blur: blur_tex_a -> blur_tex_b
+    gl.bind_framebuffer(WebGl2RenderingContext::FRAMEBUFFER, Some(&bloom.blur_fbo_b));
+    gl.bind_texture(WebGl2RenderingContext::TEXTURE_2D, Some(&bloom.blur_tex_a));
+    gl.uniform2f(Some(&bloom.blur_direction_loc), 0.0, 1.0);
+    gl.draw_arrays(WebGl2RenderingContext::TRIANGLES, 0, 6);
+
+    // Composite: sharp scene + blurred bloom, additive, straight to the screen. The screen
+    // must be cleared first: the scene texture is transparent outside the bars, so without
+    // this the additive blend leaves last frame's pixels showing through as ghosting.
+    gl.bind_framebuffer(WebGl2RenderingContext::FRAMEBUFFER, None);
+    gl.viewport(0, 0, width as i32, height as i32);
+    gl.clear_color(0.0, 0.0, 0.0, 0.0);
+    gl.clear(WebGl2RenderingContext::COLOR_BUFFER_BIT);
+    gl.use_program(Some(&bloom.composite_program));
+    bind_quad_attribs(gl, &bloom.composite_program);
+    gl.active_texture(WebGl2RenderingContext::TEXTURE0);
+    gl.bind_texture(WebGl2RenderingContext::TEXTURE_2D, Some(&bloom.scene_tex));
+    gl.active_texture(WebGl2RenderingContext::TEXTURE1);
+    gl.bind_texture(WebGl2RenderingContext::TEXTURE_2D, Some(&bloom.blur_tex_b));
+    gl.uniform1f(Some(&bloom.composite_intensity_loc), bloom.intensity);
+    gl.enable(WebGl2RenderingContext::BLEND);
+    gl.blend_func(WebGl2RenderingContext::ONE, WebGl2RenderingContext::ONE);
+    gl.draw_arrays(WebGl2RenderingContext::TRIANGLES, 0, 6);
+    // Restore the alpha blending the bar pass expects next frame.
+    gl.blend_func(WebGl2RenderingContext::SRC_ALPHA, WebGl2RenderingContext::ONE_MINUS_SRC_ALPHA);
+}
+
+fn bind_quad_attribs(gl: &WebGl2RenderingContext, program: &WebGlProgram) {
+    let pos_loc = gl.get_attrib_location(program, "a_quad_pos") as u32;
+    gl.vertex_attrib_pointer_with_i32(pos_loc, 2, WebGl2RenderingContext::FLOAT, false, 0, 0);
+    gl.enable_vertex_attrib_array(pos_loc);
+}
+
+fn create_bloom(gl: &WebGl2RenderingContext, width: f32, height: f32) -> Result<Bloom, JsValue> {
+    let (scene_fbo, scene_tex) = create_color_fbo(gl, width, height)?;
+    let (bright_fbo, bright_tex) = create_color_fbo(gl, width, height)?;
+    let (blur_fbo_a, blur_tex_a) = create_color_fbo(gl, width, height)?;
+    let (blur_fbo_b, blur_tex_b) = create_color_fbo(gl, width, height)?;
+
+    let quad_buffer = gl.create_buffer().ok_or("Failed to create quad buffer")?;
+    gl.bind_buffer(WebGl2RenderingContext::ARRAY_BUFFER, Some(&quad_buffer));
+    // Two triangles covering clip space, used for every fullscreen pass below.
+    let quad: [f32; 12] = [
+        -1.0, -1.0, 1.0, -1.0, -1.0, 1.0,
+        -1.0, 1.0, 1.0, -1.0, 1.0, 1.0,
+    ];
+    unsafe {
+        let quad_array = js_sys::Float32Array::view(&quad);
+        gl.buffer_data_with_array_buffer_view(
+            WebGl2RenderingContext::ARRAY_BUFFER,
+            &quad_array,
+            WebGl2RenderingContext::STATIC_DRAW,
+        );
+    }
+
+    let fullscreen_vert_src = r#"#version 300 es
+        precision highp float;
+        in vec2 a_quad_pos;
+        out vec2 v_uv;
+        void main() {
+            v_uv = a_quad_pos * 0.5 + 0.5;
+            gl_Position = vec4(a_quad_pos, 0, 1);
+        }
+    "#;
+    let fullscreen_vert = compile_shader(gl, WebGl2RenderingContext::VERTEX_SHADER, fullscreen_vert_src)?;
+
+    let bright_frag_src = r#"#version 300 es
+        precision highp float;
+        in vec2 v_uv;
+        uniform sampler2D u_tex;
+        uniform vec2 u_resolution;
+        uniform float u_threshold;
+        out vec4 fragColor;
+        void main() {
+            vec4 c = texture(u_tex, v_uv);
+            float luma = dot(c.rgb, vec3(0.2126, 0.7152, 0.0722));
+            float keep = max(0.0, luma - u_threshold);
+            fragColor = vec4(c.rgb * (keep / max(luma, 0.0001)), c.a);
+        }
+    "#;
+    let bright_frag = compile_shader(gl, WebGl2RenderingContext::FRAGMENT_SHADER, bright_frag_src)?;
+    let bright_program = link_program(gl, &fullscreen_vert, &bright_frag)?;
+    gl.delete_shader(Some(&bright_frag));
+    let bright_resolution_loc = gl.get_uniform_location(&bright_program, "u_resolution").ok_or("No bright resolution uniform")?;
+    let bright_threshold_loc = gl.get_uniform_location(&bright_program, "u_threshold").ok_or("No bright threshold uniform")?;
+
+    let blur_frag_src = r#"#version 300 es
+        precision highp float;
+        in vec2 v_uv;
+        uniform sampler2D u_tex;
+        uniform vec2 u_resolution;
+        uniform vec2 u_direction;
+        uniform float u_weights[11];
+        out vec4 fragColor;
+        void main() {
+            vec2 texel = u_direction / u_resolution;
+            vec3 sum = texture(u_tex, v_uv).rgb * u_weights[0];
+            for (int k = 1; k < 11; k++) {
+                vec2 offset = texel * float(k);
+                sum += texture(u_tex, v_uv + offset).rgb * u_weights[k];
+                sum += texture(u_tex, v_uv - offset).rgb * u_weights[k];
+            }
+            fragColor = vec4(sum, 1.0);
+        }
+    "#;
+    let blur_frag = compile_shader(gl, WebGl2RenderingContext::FRAGMENT_SHADER, blur_frag_src)?;
+    let blur_program = link_program(gl, &fullscreen_vert, &blur_frag)?;
+    gl.delete_shader(Some(&blur_frag));
+    let blur_resolution_loc = gl.get_uniform_location(&blur_program, "u_resolution").ok_or("No blur resolution uniform")?;
+    let blur_direction_loc = gl.get_uniform_location(&blur_program, "u_direction").ok_or("No blur direction uniform")?;
+    let blur_weights_loc = gl.get_uniform_location(&blur_program, "u_weights").ok_or("No blur weights uniform")?;
+
+    let composite_frag_src = r#"#version 300 es
+        precision highp float;
+        in vec2 v_uv;
+        uniform sampler2D u_scene;
+        uniform sampler2D u_bloom;
+        uniform float u_intensity;
+        out vec4 fragColor;
+        void main() {
+            vec4 scene = texture(u_scene, v_uv);
+            vec3 bloom = texture(u_bloom, v_uv).rgb;
+            fragColor = vec4(scene.rgb + bloom * u_intensity, scene.a);
+        }
+    "#;
+    let composite_frag = compile_shader(gl, WebGl2RenderingContext::FRAGMENT_SHADER, composite_frag_src)?;
+    let composite_program = link_program(gl, &fullscreen_vert, &composite_frag)?;
+    gl.delete_shader(Some(&composite_frag));
+    // Shared by all three programs above; safe to delete now that the last one has linked it in.
+    gl.delete_shader(Some(&fullscreen_vert));
+    let composite_intensity_loc = gl.get_uniform_location(&composite_program, "u_intensity").ok_or("No composite intensity uniform")?;
+
+    gl.use_program(Some(&bright_program));
+    gl.uniform1i(gl.get_uniform_location(&bright_program, "u_tex").as_ref(), 0);
+    gl.use_program(Some(&blur_program));
+    gl.uniform1i(gl.get_uniform_location(&blur_program, "u_tex").as_ref(), 0);
+    gl.use_program(Some(&composite_program));
+    gl.uniform1i(gl.get_uniform_location(&composite_program, "u_scene").as_ref(), 0);
+    gl.uniform1i(gl.get_uniform_location(&composite_program, "u_bloom").as_ref(), 1);
+
+    let sigma = 3.0;
+
+    Ok(Bloom {
+        scene_fbo,
+        scene_tex,
+        bright_fbo,
+        bright_tex,
+        blur_fbo_a,
+        blur_tex_a,
+        blur_fbo_b,
+        blur_tex_b,
+        quad_buffer,
+        bright_program,
+        bright_resolution_loc,
+        bright_threshold_loc,
+        blur_program,
+        blur_resolution_loc,
+        blur_direction_loc,
+        blur_weights_loc,
+        composite_program,
+        composite_intensity_loc,
+        threshold: 0.6,
+        sigma,
+        intensity: 1.0,
+        weights: gaussian_weights(sigma, BLUR_TAPS),
+    })
+}
+
+// Releases a superseded Bloom's GPU resources. Needed because update_canvas_size() rebuilds
+// the whole pipeline at the new size on every resize, which would otherwise leak a full set
+// of FBOs/textures/programs per resize event.
+fn delete_bloom(gl: &WebGl2RenderingContext, bloom: Bloom) {
+    gl.delete_framebuffer(Some(&bloom.scene_fbo));
+    gl.delete_texture(Some(&bloom.scene_tex));
+    gl.delete_framebuffer(Some(&bloom.bright_fbo));
+    gl.delete_texture(Some(&bloom.bright_tex));
+    gl.delete_framebuffer(Some(&bloom.blur_fbo_a));
+    gl.delete_texture(Some(&bloom.blur_tex_a));
+    gl.delete_framebuffer(Some(&bloom.blur_fbo_b));
+    gl.delete_texture(Some(&bloom.blur_tex_b));
+    gl.delete_buffer(Some(&bloom.quad_buffer));
+    gl.delete_program(Some(&bloom.bright_program));
+    gl.delete_program(Some(&bloom.blur_program));
+    gl.delete_program(Some(&bloom.composite_program));
+}
+
+fn create_peak_caps(
+    gl: &WebGl2RenderingContext,
+    quad_buffer: &WebGlBuffer,
+    instance_index_buffer: &WebGlBuffer,
+) -> Result<PeakCaps, JsValue> {
+    let vert_src = r#"#version 300 es
+        precision highp float;
+
+        in vec2 a_quad_pos;
+        in float a_bar_index;
+        in float a_peak;
+
+        uniform vec2 u_resolution;
+        uniform float u_padding_x;
+        uniform float u_bar_spacing;
+        uniform float u_bar_half_width;
+        uniform float u_max_height;
+        uniform float u_cy;
+        uniform float u_thickness;
+        uniform float u_sign;
+
+        out float v_index;
+
+        void main() {
+            float peakHeight = 4.0 + a_peak * u_max_height;
+            float centerY = u_cy + u_sign * peakHeight;
+            vec2 halfExtent = vec2(u_bar_half_width, u_thickness);
+            vec2 local = a_quad_pos * halfExtent;
+
+            float xCenter = u_padding_x + a_bar_index * u_bar_spacing + u_bar_spacing * 0.5;
+            vec2 worldPos = vec2(xCenter, centerY) + local;
+
+            v_index = a_bar_index;
+
+            vec2 clipSpace = (worldPos / u_resolution) * 2.0 - 1.0;
+            gl_Position = vec4(clipSpace * vec2(1, -1), 0, 1);
+        }
+    "#;
+    let vert_shader = compile_shader(gl, WebGl2RenderingContext::VERTEX_SHADER, vert_src)?;
+
+    let frag_src = r#"#version 300 es
+        precision highp float;
+
+        in float v_index;
+        out vec4 fragColor;
+
+        void main() {
+            // Same cyan -> indigo -> pink ramp as the bars, so the cap reads as part of them.
+            vec3 c1 = vec3(0.0, 0.84, 1.0);
+            vec3 c2 = vec3(0.39, 0.4, 0.95);
+            vec3 c3 = vec3(1.0, 0.18, 0.58);
+
+            float t = v_index / 128.0;
+            vec3 color = mix(c1, c2, smoothstep(0.0, 0.5, t));
+            color = mix(color, c3, smoothstep(0.5, 1.0, t));
+
+            fragColor = vec4(color, 1.0);
+        }
+    "#;
+    let frag_shader = compile_shader(gl, WebGl2RenderingContext::FRAGMENT_SHADER, frag_src)?;
+    let program = link_program(gl, &vert_shader, &frag_shader)?;
+
+    gl.use_program(Some(&program));
+
+    let resolution_loc = gl.get_uniform_location(&program, "u_resolution").ok_or("No cap resolution uniform")?;
+    let padding_x_loc = gl.get_uniform_location(&program, "u_padding_x").ok_or("No cap padding uniform")?;
+    let bar_spacing_loc = gl.get_uniform_location(&program, "u_bar_spacing").ok_or("No cap bar spacing uniform")?;
+    let bar_half_width_loc = gl.get_uniform_location(&program, "u_bar_half_width").ok_or("No cap half width uniform")?;
+    let max_height_loc = gl.get_uniform_location(&program, "u_max_height").ok_or("No cap max height uniform")?;
+    let cy_loc = gl.get_uniform_location(&program, "u_cy").ok_or("No cap cy uniform")?;
+    let thickness_loc = gl.get_uniform_location(&program, "u_thickness").ok_or("No cap thickness uniform")?;
+    let sign_loc = gl.get_uniform_location(&program, "u_sign").ok_or("No cap sign uniform")?;
+
+    let quad_loc = gl.get_attrib_location(&program, "a_quad_pos") as u32;
+    let idx_loc = gl.get_attrib_location(&program, "a_bar_index") as u32;
+    let peak_loc = gl.get_attrib_location(&program, "a_peak") as u32;
+
+    // Reuse the bar program's static quad and index buffers, just re-pointed at this program's
+    // attribute locations.
+    gl.bind_buffer(WebGl2RenderingContext::ARRAY_BUFFER, Some(quad_buffer));
+    gl.vertex_attrib_pointer_with_i32(quad_loc, 2, WebGl2RenderingContext::FLOAT, false, 0, 0);
+    gl.enable_vertex_attrib_array(quad_loc);
+
+    gl.bind_buffer(WebGl2RenderingContext::ARRAY_BUFFER, Some(instance_index_buffer));
+    gl.vertex_attrib_pointer_with_i32(idx_loc, 1, WebGl2RenderingContext::FLOAT, false, 0, 0);
+    gl.enable_vertex_attrib_array(idx_loc);
+    gl.vertex_attrib_divisor(idx_loc, 1);
+
+    let instance_peak_buffer = gl.create_buffer().ok_or("Failed to create instance peak buffer")?;
+    gl.bind_buffer(WebGl2RenderingContext::ARRAY_BUFFER, Some(&instance_peak_buffer));
+    gl.vertex_attrib_pointer_with_i32(peak_loc, 1, WebGl2RenderingContext::FLOAT, false, 0, 0);
+    gl.enable_vertex_attrib_array(peak_loc);
+    gl.vertex_attrib_divisor(peak_loc, 1);
+
+    Ok(PeakCaps {
+        program,
+        instance_peak_buffer,
+        resolution_loc,
+        padding_x_loc,
+        bar_spacing_loc,
+        bar_half_width_loc,
+        max_height_loc,
+        cy_loc,
+        thickness_loc,
+        sign_loc,
+        thickness: 1.5,
+    })
+}
+
+fn create_color_fbo(gl: &WebGl2RenderingContext, width: f32, height: f32) -> Result<(WebGlFramebuffer, WebGlTexture), JsValue> {
+    let tex = gl.create_texture().ok_or("Failed to create texture")?;
+    gl.bind_texture(WebGl2RenderingContext::TEXTURE_2D, Some(&tex));
+    gl.tex_image_2d_with_i32_and_i32_and_i32_and_format_and_type_and_opt_u8_array(
+        WebGl2RenderingContext::TEXTURE_2D,
+        0,
+        WebGl2RenderingContext::RGBA as i32,
+        width.max(1.0) as i32,
+        height.max(1.0) as i32,
+        0,
+        WebGl2RenderingContext::RGBA,
+        WebGl2RenderingContext::UNSIGNED_BYTE,
+        None,
+    )?;
+    gl.tex_parameteri(WebGl2RenderingContext::TEXTURE_2D, WebGl2RenderingContext::TEXTURE_MIN_FILTER, WebGl2RenderingContext::LINEAR as i32);
+    gl.tex_parameteri(WebGl2RenderingContext::TEXTURE_2D, WebGl2RenderingContext::TEXTURE_MAG_FILTER, WebGl2RenderingContext::LINEAR as i32);
+    gl.tex_parameteri(WebGl2RenderingContext::TEXTURE_2D, WebGl2RenderingContext::TEXTURE_WRAP_S, WebGl2RenderingContext::CLAMP_TO_EDGE as i32);
+    gl.tex_parameteri(WebGl2RenderingContext::TEXTURE_2D, WebGl2RenderingContext::TEXTURE_WRAP_T, WebGl2RenderingContext::CLAMP_TO_EDGE as i32);
+
+    let fbo = gl.create_framebuffer().ok_or("Failed to create framebuffer")?;
+    gl.bind_framebuffer(WebGl2RenderingContext::FRAMEBUFFER, Some(&fbo));
+    gl.framebuffer_texture_2d(
+        WebGl2RenderingContext::FRAMEBUFFER,
+        WebGl2RenderingContext::COLOR_ATTACHMENT0,
+        WebGl2RenderingContext::TEXTURE_2D,
+        Some(&tex),
+        0,
+    );
+    gl.bind_framebuffer(WebGl2RenderingContext::FRAMEBUFFER, None);
+
+    Ok((fbo, tex))
+}
+
+// Precompute normalized Gaussian tap weights: w_k = exp(-k^2 / (2*sigma^2)), w[0] is the center tap.
+fn gaussian_weights(sigma: f32, taps: usize) -> Vec<f32> {
+    let sigma = sigma.max(0.0001);
+    let mut weights: Vec<f32> = (0..taps)
+        .map(|k| (-((k * k) as f32) / (2.0 * sigma * sigma)).exp())
+        .collect();
+    let sum: f32 = weights[0] + 2.0 * weights[1..].iter().sum::<f32>();
+    for w in weights.iter_mut() {
+        *w /= sum;
+    }
+    weights
+}
+
+// Points the bar program's instanced attributes at their buffers. Called both at startup and
+// whenever set_fragment_shader() links a new program, since attribute locations are re-assigned
+// per-program even when the underlying vertex shader source hasn't changed.
+fn bind_bar_attribs(
+    gl: &WebGl2RenderingContext,
+    program: &WebGlProgram,
+    quad_buffer: &WebGlBuffer,
+    instance_index_buffer: &WebGlBuffer,
+    instance_value_buffer: &WebGlBuffer,
+) {
+    let quad_loc = gl.get_attrib_location(program, "a_quad_pos") as u32;
+    let val_loc = gl.get_attrib_location(program, "a_value") as u32;
+    let idx_loc = gl.get_attrib_location(program, "a_bar_index") as u32;
+
+    gl.bind_buffer(WebGl2RenderingContext::ARRAY_BUFFER, Some(quad_buffer));
+    gl.vertex_attrib_pointer_with_i32(quad_loc, 2, WebGl2RenderingContext::FLOAT, false, 0, 0);
+    gl.enable_vertex_attrib_array(quad_loc);
+
+    gl.bind_buffer(WebGl2RenderingContext::ARRAY_BUFFER, Some(instance_index_buffer));
+    gl.vertex_attrib_pointer_with_i32(idx_loc, 1, WebGl2RenderingContext::FLOAT, false, 0, 0);
+    gl.enable_vertex_attrib_array(idx_loc);
+    gl.vertex_attrib_divisor(idx_loc, 1);
+
+    gl.bind_buffer(WebGl2RenderingContext::ARRAY_BUFFER, Some(instance_value_buffer));
+    gl.vertex_attrib_pointer_with_i32(val_loc, 1, WebGl2RenderingContext::FLOAT, false, 0, 0);
+    gl.enable_vertex_attrib_array(val_loc);
+    gl.vertex_attrib_divisor(val_loc, 1);
 }
 
 fn compile_shader(gl: &WebGl2RenderingContext, shader_type: u32, source: &str) -> Result<WebGlShader, String> {
     let shader = gl.create_shader(shader_type).ok_or("Cannot create shader")?;
     gl.shader_source(&shader, source);
     gl.compile_shader(&shader);
-    
+
     if gl.get_shader_parameter(&shader, WebGl2RenderingContext::COMPILE_STATUS)
         .as_bool()
         .unwrap_or(false)
@@ -249,7 +1190,7 @@ fn link_program(gl: &WebGl2RenderingContext, vert: &WebGlShader, frag: &WebGlSha
     gl.attach_shader(&program, vert);
     gl.attach_shader(&program, frag);
     gl.link_program(&program);
-    
+
     if gl.get_program_parameter(&program, WebGl2RenderingContext::LINK_STATUS)
         .as_bool()
         .unwrap_or(false)
@@ -259,3 +1200,119 @@ fn link_program(gl: &WebGl2RenderingContext, vert: &WebGlShader, frag: &WebGlSha
         Err(gl.get_program_info_log(&program).unwrap_or_default())
     }
 }
+
+// Software fallback: draws the same rounded-pill bars via the 2D canvas API, approximating the
+// GL bloom pass with `shadow_blur` since there's no offscreen framebuffer to blur.
+fn render_canvas2d(state: &mut Canvas2dState) {
+    let ctx = &state.ctx;
+    let layout = compute_bar_layout(state.canvas_width, state.canvas_height);
+
+    ctx.clear_rect(0.0, 0.0, state.canvas_width as f64, state.canvas_height as f64);
+
+    let gradient = ctx.create_linear_gradient(0.0, 0.0, state.canvas_width as f64, 0.0);
+    let _ = gradient.add_color_stop(0.0, "#00d7ff"); // Cyan
+    let _ = gradient.add_color_stop(0.5, "#6366f1"); // Indigo
+    let _ = gradient.add_color_stop(1.0, "#ff2d95"); // Pink
+    ctx.set_fill_style_canvas_gradient(&gradient);
+    ctx.set_shadow_color("#6366f1");
+
+    let corner_radius = state.dynamics.corner_radius.max(0.0);
+
+    for i in 0..NUM_BARS {
+        let value = state.dynamics.smoothed[i];
+        let h = 4.0 + value * layout.max_height;
+        let peak_h = 4.0 + state.dynamics.peak[i] * layout.max_height;
+        let x_center = layout.padding_x + i as f32 * layout.bar_spacing + layout.bar_spacing * 0.5;
+
+        let x = (x_center - layout.bar_half_width) as f64;
+        let y = (layout.cy - h) as f64;
+        let w = (layout.bar_half_width * 2.0) as f64;
+        let bar_h = (h * 2.0) as f64;
+        let radius = corner_radius.min(layout.bar_half_width).min(h) as f64;
+
+        ctx.set_shadow_blur((4.0 + value * 16.0) as f64);
+        draw_rounded_rect_path(ctx, x, y, w, bar_h, radius);
+        ctx.fill();
+
+        // Peak-hold cap, mirrored above and below the bar like the GL peak caps.
+        ctx.set_shadow_blur(0.0);
+        ctx.fill_rect(x, (layout.cy - peak_h - 1.5) as f64, w, 3.0);
+        ctx.fill_rect(x, (layout.cy + peak_h - 1.5) as f64, w, 3.0);
+    }
+}
+
+fn draw_rounded_rect_path(ctx: &CanvasRenderingContext2d, x: f64, y: f64, w: f64, h: f64, r: f64) {
+    let r = r.max(0.0).min(w / 2.0).min(h / 2.0);
+    ctx.begin_path();
+    ctx.move_to(x + r, y);
+    ctx.line_to(x + w - r, y);
+    let _ = ctx.arc_to(x + w, y, x + w, y + r, r);
+    ctx.line_to(x + w, y + h - r);
+    let _ = ctx.arc_to(x + w, y + h, x + w - r, y + h, r);
+    ctx.line_to(x + r, y + h);
+    let _ = ctx.arc_to(x, y + h, x, y + h - r, r);
+    ctx.line_to(x, y + r);
+    let _ = ctx.arc_to(x, y, x + r, y, r);
+    ctx.close_path();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bin_ranges_cover_every_bar_in_order() {
+        for scale in [ScaleMode::Linear, ScaleMode::Log, ScaleMode::Mel] {
+            let ranges = compute_bin_ranges(1024, scale, 20.0, 20000.0, 44100.0);
+            assert_eq!(ranges.len(), NUM_BARS);
+            for (i, &(start, end)) in ranges.iter().enumerate() {
+                assert!(start < end, "bar {i} has empty range {start}..{end}");
+                assert!(end <= 1024, "bar {i} range {start}..{end} exceeds num_bins");
+            }
+            for w in ranges.windows(2) {
+                assert!(w[0].0 <= w[1].0, "bar ranges must not go backwards");
+            }
+        }
+    }
+
+    #[test]
+    fn bin_ranges_degenerate_below_two_bins() {
+        let ranges = compute_bin_ranges(1, ScaleMode::Linear, 20.0, 20000.0, 44100.0);
+        assert_eq!(ranges, vec![(0, 1); NUM_BARS]);
+
+        let ranges = compute_bin_ranges(0, ScaleMode::Log, 20.0, 20000.0, 44100.0);
+        assert_eq!(ranges, vec![(0, 0); NUM_BARS]);
+    }
+
+    #[test]
+    fn log_scale_spreads_low_bins_wider_than_high_bins() {
+        let ranges = compute_bin_ranges(1024, ScaleMode::Log, 20.0, 20000.0, 44100.0);
+        let first_width = ranges[0].1 - ranges[0].0;
+        let last_width = ranges[NUM_BARS - 1].1 - ranges[NUM_BARS - 1].0;
+        assert!(
+            last_width >= first_width,
+            "log scale should widen toward high frequencies: first={first_width} last={last_width}"
+        );
+    }
+
+    #[test]
+    fn gaussian_weights_are_normalized() {
+        for sigma in [0.0001, 1.0, 3.0, 10.0] {
+            let weights = gaussian_weights(sigma, BLUR_TAPS);
+            assert_eq!(weights.len(), BLUR_TAPS);
+            let total: f32 = weights[0] + 2.0 * weights[1..].iter().sum::<f32>();
+            assert!(
+                (total - 1.0).abs() < 1e-5,
+                "weights for sigma={sigma} sum to {total}, expected 1.0"
+            );
+        }
+    }
+
+    #[test]
+    fn gaussian_weights_decrease_away_from_center() {
+        let weights = gaussian_weights(3.0, BLUR_TAPS);
+        for w in weights.windows(2) {
+            assert!(w[0] >= w[1], "weights should be non-increasing away from the center tap");
+        }
+    }
+}